@@ -1,10 +1,11 @@
 //! Clout is a *c*ommand *l*ine *out*put library.
 //!
 //! It provides a similar interface to the logging crate but with a different focus:
-//! * clout's output is opinionated and not pluggable like logging
+//! * clout's output is opinionated, with sensible defaults, rather than pluggable like logging
+//! * the destination can still be swapped out via the [Backend] trait, e.g. for tests
 //! * clout provides output with sensible settings for use in command line tools
 //!    * colours are supported for different message levels
-//!    * output is always to stdout (for now)
+//!    * errors and warnings go to stderr, everything else goes to stdout
 //!
 //! Many libraries already output messages to the logging framework, and you generally
 //! don't want all these messages to get displayed to the end user. Clout allows you to
@@ -17,6 +18,7 @@
 //! a common practice) but logging only provides two levels below info.
 
 use std::fmt::{self, Display};
+use std::str::FromStr;
 use std::error::Error;
 use std::io::Write;
 use termcolor::{WriteColor, ColorSpec, Color, StandardStream, ColorChoice};
@@ -25,7 +27,7 @@ use std::sync::Mutex;
 
 /// The different levels of importance of a message
 /// Also used to determine what level of messages should be displayed
-#[derive(PartialOrd, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
 pub enum Level {
     /// Display absolutely nothing
     Silent,
@@ -47,6 +49,30 @@ pub enum Level {
 }
 
 impl Level {
+    /// Short uppercase label used for the `[WARN]`-style line prefix, see
+    /// [Builder::with_level_labels].
+    fn label(&self) -> &'static str {
+        match self {
+            Level::Silent => "SILENT",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Status => "STATUS",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    /// Step `n` levels more verbose than `self`, saturating at `Trace`.
+    fn step(self, n: u8) -> Level {
+        const ORDER: [Level; 7] = [
+            Level::Silent, Level::Error, Level::Warn, Level::Status,
+            Level::Info, Level::Debug, Level::Trace,
+        ];
+        let index = ORDER.iter().position(|level| *level == self).unwrap();
+        ORDER[(index + n as usize).min(ORDER.len() - 1)]
+    }
+
     fn get_color(&self) -> ColorSpec {
         let mut spec = ColorSpec::new();
 
@@ -75,7 +101,81 @@ impl Level {
     }
 }
 
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let name = match self {
+            Level::Silent => "silent",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Status => "status",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Returned by [Level]'s [FromStr] implementation when the string doesn't
+/// name a valid level.
+#[derive(Debug)]
+pub struct LevelParseError(String);
+
+impl Display for LevelParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "'{}' is not a valid clout level", self.0)
+    }
+}
+
+impl Error for LevelParseError {}
+
+/// Implemented by zero-sized marker types naming the level that `-v`
+/// counting should start from. See [Builder::with_verbose_as].
+pub trait VerbosityDefault {
+    /// The level used when the verbosity count is zero.
+    fn base() -> Level;
+}
+
+/// Count verbosity from `Level::Status`, clout's own default.
+pub struct StatusLevel;
+impl VerbosityDefault for StatusLevel {
+    fn base() -> Level { Level::Status }
+}
+
+/// Count verbosity from `Level::Info`, for tools that are chatty by default.
+pub struct InfoLevel;
+impl VerbosityDefault for InfoLevel {
+    fn base() -> Level { Level::Info }
+}
+
+/// Count verbosity from `Level::Error`, for tools (e.g. daemons) that
+/// should be quiet unless asked otherwise.
+pub struct ErrorLevel;
+impl VerbosityDefault for ErrorLevel {
+    fn base() -> Level { Level::Error }
+}
+
+impl FromStr for Level {
+    type Err = LevelParseError;
+
+    /// Parse a level from its name, case-insensitively, e.g. for reading a
+    /// level from `$CLOUT_LEVEL` or a `--log-level` argument.
+    fn from_str(s: &str) -> Result<Level, LevelParseError> {
+        match s.to_lowercase().as_str() {
+            "silent" => Ok(Level::Silent),
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "status" => Ok(Level::Status),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(LevelParseError(s.to_string())),
+        }
+    }
+}
+
 /// Determine if clout should use colors for output
+#[derive(Clone, Copy)]
 pub enum UseColor {
     /// Never use colour
     Never,
@@ -85,12 +185,15 @@ pub enum UseColor {
     Auto,
 }
 
-impl Into<ColorChoice> for UseColor {
-    fn into(self) -> ColorChoice {
+impl UseColor {
+    /// Resolve this setting into a [ColorChoice] for a specific stream.
+    /// `Auto` is resolved independently per-stream, so a piped stdout
+    /// doesn't disable colour on a terminal stderr (or vice versa).
+    fn resolve(&self, stream: atty::Stream) -> ColorChoice {
         match self {
             UseColor::Never => ColorChoice::Never,
-            UseColor::Always => ColorChoice::Auto,
-            UseColor::Auto => if atty::is(atty::Stream::Stdout) {
+            UseColor::Always => ColorChoice::Always,
+            UseColor::Auto => if atty::is(stream) {
                 ColorChoice::Auto
             } else {
                 ColorChoice::Never
@@ -121,9 +224,174 @@ impl Display for CloutError {
 impl Error for CloutError {}
 
 
+/// A `Backend` is responsible for actually delivering a message somewhere -
+/// a terminal, an in-memory buffer, another logging framework, etc...
+///
+/// clout ships with [TermcolorBackend] as the default, but a custom backend
+/// can be installed with [Builder::with_backend] to capture output for
+/// tests, forward it elsewhere, or do anything else `termcolor` can't.
+///
+/// `Backend` requires `Send` because the installed backend lives behind the
+/// global `Mutex` clout uses to support being called from any thread.
+pub trait Backend: Send {
+    /// Report a message at the given level. Implementations should not
+    /// filter by level themselves - clout only calls this once the message
+    /// has already passed the configured level threshold.
+    fn report(&mut self, level: Level, args: fmt::Arguments);
+
+    /// Report an "action" message, cargo-style, e.g. `   Compiling foo`.
+    /// `action` is the verb (`"Compiling"`) and `width` is the column it
+    /// should be right-aligned to.
+    ///
+    /// The default implementation just folds the action into the message
+    /// and calls [Backend::report]; [TermcolorBackend] overrides this to
+    /// right-align and colour the verb.
+    fn report_action(&mut self, level: Level, action: &str, width: usize, args: fmt::Arguments) {
+        self.report(level, format_args!("{:>width$} {}", action, args, width = width));
+    }
+}
+
+/// Controls whether each line gets a timestamp prefix, and in what format.
+pub enum TimestampFormat {
+    /// No timestamp
+    None,
+    /// Just the time of day, e.g. `15:04:05`
+    Time,
+    /// A full RFC3339 timestamp in local time, with sub-second digits when
+    /// the fraction is non-zero, e.g. `2023-01-02T15:04:05.123456789+00:00`
+    Rfc3339,
+}
+
+impl TimestampFormat {
+    fn render(&self) -> Option<String> {
+        match self {
+            TimestampFormat::None => None,
+            TimestampFormat::Time => Some(chrono::Local::now().format("%H:%M:%S").to_string()),
+            TimestampFormat::Rfc3339 => Some(chrono::Local::now().to_rfc3339()),
+        }
+    }
+}
+
+/// The default [Backend], writing coloured output to a [StandardStream].
+///
+/// Messages at or below the configured `stderr_from` level (see
+/// [Builder::with_stderr_from]) are written to stderr, everything else goes
+/// to stdout, so diagnostics survive shell piping of a tool's real output.
+pub struct TermcolorBackend {
+    stdout: StandardStream,
+    stderr: StandardStream,
+    stderr_from: Level,
+    timestamp: TimestampFormat,
+    level_labels: bool,
+    prefix_every_line: bool,
+}
+
+impl TermcolorBackend {
+    /// Construct a `TermcolorBackend`, using `use_color` to decide whether
+    /// colour is enabled (independently for stdout and stderr), and
+    /// `stderr_from` to decide which levels are routed to stderr.
+    pub fn new(use_color: UseColor, stderr_from: Level) -> TermcolorBackend {
+        TermcolorBackend {
+            stdout: StandardStream::stdout(use_color.resolve(atty::Stream::Stdout)),
+            stderr: StandardStream::stderr(use_color.resolve(atty::Stream::Stderr)),
+            stderr_from,
+            timestamp: TimestampFormat::None,
+            level_labels: false,
+            prefix_every_line: false,
+        }
+    }
+
+    /// Prefix each message with a timestamp, formatted per `format`.
+    pub fn with_timestamp(mut self, format: TimestampFormat) -> TermcolorBackend {
+        self.timestamp = format;
+        self
+    }
+
+    /// Prefix each message with a `[LEVEL]` label.
+    pub fn with_level_labels(mut self, level_labels: bool) -> TermcolorBackend {
+        self.level_labels = level_labels;
+        self
+    }
+
+    /// If `true`, repeat the timestamp/label prefix on every line of a
+    /// multi-line message. If `false` (the default), only the first line
+    /// gets a prefix.
+    pub fn with_prefix_every_line(mut self, prefix_every_line: bool) -> TermcolorBackend {
+        self.prefix_every_line = prefix_every_line;
+        self
+    }
+
+    fn stream(&mut self, level: Level) -> &mut StandardStream {
+        if level <= self.stderr_from {
+            &mut self.stderr
+        } else {
+            &mut self.stdout
+        }
+    }
+
+    fn write_prefix(&mut self, level: Level) {
+        if let Some(ts) = self.timestamp.render() {
+            let mut dim = ColorSpec::new();
+            dim.set_dimmed(true);
+
+            let write = self.stream(level);
+            let _ = write.set_color(&dim);
+            let _ = write!(write, "{} ", ts);
+            let _ = write.reset();
+        }
+
+        if self.level_labels {
+            let write = self.stream(level);
+            let _ = write.set_color(&level.get_color());
+            let _ = write!(write, "[{}] ", level.label());
+            let _ = write.reset();
+        }
+    }
+
+    fn write_line(&mut self, level: Level, with_prefix: bool, line: &str) {
+        if with_prefix {
+            self.write_prefix(level);
+        }
+
+        let write = self.stream(level);
+        let _ = write.set_color(&level.get_color());
+        let _ = write!(write, "{}", line);
+        let _ = writeln!(write);
+        let _ = write.reset();
+    }
+}
+
+impl Backend for TermcolorBackend {
+    fn report(&mut self, level: Level, args: fmt::Arguments) {
+        let message = args.to_string();
+        let mut lines = message.lines();
+        let first = lines.next().unwrap_or("");
+
+        self.write_line(level, true, first);
+        for line in lines {
+            self.write_line(level, self.prefix_every_line, line);
+        }
+    }
+
+    fn report_action(&mut self, level: Level, action: &str, width: usize, args: fmt::Arguments) {
+        self.write_prefix(level);
+
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(Color::Green)).set_bold(true);
+
+        let write = self.stream(level);
+        let _ = write.set_color(&spec);
+        let _ = write!(write, "{:>width$}", action, width = width);
+        let _ = write.reset();
+        let _ = write!(write, " {}", args);
+        let _ = writeln!(write);
+    }
+}
+
 struct Clout {
     level: Level,
-    write: StandardStream,
+    backend: Box<dyn Backend + Send>,
+    action_width: usize,
 }
 
 lazy_static! {
@@ -134,12 +402,27 @@ lazy_static! {
 pub struct Builder {
     level: Level,
     use_color: UseColor,
+    stderr_from: Level,
+    timestamp: TimestampFormat,
+    level_labels: bool,
+    prefix_every_line: bool,
+    action_width: usize,
+    backend: Option<Box<dyn Backend + Send>>,
 }
 
 impl Builder {
     /// Construct a new builder with default (Status level, Auto colour)
     pub fn new() -> Builder {
-        Self { level: Level::Status, use_color: UseColor::Auto }
+        Self {
+            level: Level::Status,
+            use_color: UseColor::Auto,
+            stderr_from: Level::Warn,
+            timestamp: TimestampFormat::None,
+            level_labels: false,
+            prefix_every_line: false,
+            action_width: 12,
+            backend: None,
+        }
     }
 
     /// Set the message level
@@ -148,20 +431,42 @@ impl Builder {
         self
     }
 
-    /// Set the message level from a verbosity flag
-    /// This is useful for supporting flags like `-v`, `-vv` etc...
+    /// Set the message level by parsing the environment variable named
+    /// `var_name` (e.g. `$CLOUT_LEVEL`), using [Level]'s [FromStr] impl.
+    /// If the variable isn't set, or doesn't parse to a valid level, the
+    /// current level is left unchanged.
+    pub fn with_level_from_env(mut self, var_name: &str) -> Builder {
+        if let Ok(value) = std::env::var(var_name) {
+            if let Ok(level) = value.parse() {
+                self.level = level;
+            }
+        }
+        self
+    }
+
+    /// Set the message level from a verbosity flag, counting up from
+    /// `Level::Status`. This is useful for supporting flags like `-v`,
+    /// `-vv` etc...
     ///
     /// * 0 (the default) => Status
     /// * 1 => Info level
     /// * 2 => Debug
     /// * 3 or greater => Trace
-    pub fn with_verbose(mut self, verbose: u8) -> Builder {
-        self.level = match verbose {
-            0 => Level::Status,
-            1 => Level::Info,
-            2 => Level::Debug,
-            _ => Level::Trace,
-        };
+    ///
+    /// Equivalent to `self.with_verbose_as::<StatusLevel>(verbose)`. Use
+    /// [Builder::with_verbose_as] directly if the quiet baseline for your
+    /// tool isn't `Status`.
+    pub fn with_verbose(self, verbose: u8) -> Builder {
+        self.with_verbose_as::<StatusLevel>(verbose)
+    }
+
+    /// Set the message level from a verbosity flag, counting up from
+    /// `L::base()` instead of the `Status` level `with_verbose` assumes.
+    /// For example a daemon might use `with_verbose_as::<ErrorLevel>(verbose)`
+    /// so it stays quiet by default, while a chatty tool might use
+    /// `with_verbose_as::<InfoLevel>(verbose)`.
+    pub fn with_verbose_as<L: VerbosityDefault>(mut self, verbose: u8) -> Builder {
+        self.level = L::base().step(verbose);
         self
     }
 
@@ -190,10 +495,68 @@ impl Builder {
         self
     }
 
+    /// Route messages at or below `level` to stderr, and everything above
+    /// it to stdout. Defaults to `Level::Warn`, so `Error` and `Warn`
+    /// messages go to stderr while `Status`/`Info`/`Debug`/`Trace` stay on
+    /// stdout. Only applies to the default [TermcolorBackend]; has no
+    /// effect if a custom backend is installed with [Builder::with_backend].
+    pub fn with_stderr_from(mut self, level: Level) -> Builder {
+        self.stderr_from = level;
+        self
+    }
+
+    /// Prefix each message with a timestamp, formatted per `format`.
+    /// Off by default, so existing output is unaffected. Only applies to
+    /// the default [TermcolorBackend].
+    pub fn with_timestamp(mut self, format: TimestampFormat) -> Builder {
+        self.timestamp = format;
+        self
+    }
+
+    /// Prefix each message with a `[LEVEL]` label, e.g. `[WARN]`. Off by
+    /// default. Only applies to the default [TermcolorBackend].
+    pub fn with_level_labels(mut self, level_labels: bool) -> Builder {
+        self.level_labels = level_labels;
+        self
+    }
+
+    /// If `true`, repeat the timestamp/label prefix on every line of a
+    /// multi-line message, rather than just the first. Only applies to
+    /// the default [TermcolorBackend].
+    pub fn with_prefix_every_line(mut self, prefix_every_line: bool) -> Builder {
+        self.prefix_every_line = prefix_every_line;
+        self
+    }
+
+    /// Set the column width that [emit_action] right-aligns its action verb
+    /// to. Defaults to 12, matching cargo's own output (`   Compiling foo`).
+    pub fn with_action_width(mut self, action_width: usize) -> Builder {
+        self.action_width = action_width;
+        self
+    }
+
+    /// Install a custom [Backend] instead of the default [TermcolorBackend].
+    /// Useful for capturing output in tests, or forwarding it somewhere
+    /// other than stdout.
+    pub fn with_backend(mut self, backend: Box<dyn Backend + Send>) -> Builder {
+        self.backend = Some(backend);
+        self
+    }
+
     fn build(self) -> Clout {
+        let backend = match self.backend {
+            Some(backend) => backend,
+            None => Box::new(
+                TermcolorBackend::new(self.use_color, self.stderr_from)
+                    .with_timestamp(self.timestamp)
+                    .with_level_labels(self.level_labels)
+                    .with_prefix_every_line(self.prefix_every_line),
+            ),
+        };
         Clout {
             level: self.level,
-            write: StandardStream::stdout(self.use_color.into()),
+            backend,
+            action_width: self.action_width,
         }
     }
 
@@ -253,10 +616,20 @@ pub fn emit(level: Level, args: fmt::Arguments) {
             return;
         }
 
-        clout.write.set_color(&level.get_color());
-        clout.write.write_fmt(args);
-        writeln!(clout.write);
-        clout.write.reset();
+        clout.backend.report(level, args);
+    });
+}
+
+/// Emit a cargo-style action message with a given level, e.g.
+/// `   Compiling foo`. Prefer the [action!] and [status_action!] macros.
+pub fn emit_action(level: Level, action: &str, args: fmt::Arguments) {
+    with_clout(|clout| {
+        if clout.level < level {
+            return;
+        }
+
+        let width = clout.action_width;
+        clout.backend.report_action(level, action, width, args);
     });
 }
 
@@ -295,3 +668,116 @@ macro_rules! debug {
 macro_rules! trace {
     ($($args:tt),+) => ($crate::emit($crate::Level::Trace, format_args!($($args),+)))
 }
+
+/// Emit a cargo-style action message at a given level, e.g.
+/// `action!(Level::Debug, "Skipping", "{} (up to date)", name)`.
+#[macro_export]
+macro_rules! action {
+    ($level:expr, $action:expr, $($args:tt),+) => (
+        $crate::emit_action($level, $action, format_args!($($args),+))
+    )
+}
+
+/// Emit a cargo-style action message at the `Status` level, e.g.
+/// `status_action!("Compiling", "{} v{}", name, version)`.
+#[macro_export]
+macro_rules! status_action {
+    ($action:expr, $($args:tt),+) => (
+        $crate::emit_action($crate::Level::Status, $action, format_args!($($args),+))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_roundtrips_through_display_and_from_str() {
+        let levels = [
+            Level::Silent, Level::Error, Level::Warn, Level::Status,
+            Level::Info, Level::Debug, Level::Trace,
+        ];
+        for level in levels {
+            let parsed: Level = level.to_string().parse().unwrap();
+            assert_eq!(parsed, level);
+        }
+    }
+
+    #[test]
+    fn level_from_str_is_case_insensitive() {
+        assert_eq!("WARN".parse::<Level>().unwrap(), Level::Warn);
+        assert_eq!("Debug".parse::<Level>().unwrap(), Level::Debug);
+    }
+
+    #[test]
+    fn level_from_str_rejects_unknown_names() {
+        assert!("verbose".parse::<Level>().is_err());
+    }
+
+    #[test]
+    fn with_verbose_as_steps_up_from_the_marker_base() {
+        let base = Builder::new().with_verbose_as::<ErrorLevel>(0);
+        assert_eq!(base.level, Level::Error);
+
+        let one = Builder::new().with_verbose_as::<ErrorLevel>(1);
+        assert_eq!(one.level, Level::Warn);
+
+        let chatty = Builder::new().with_verbose_as::<InfoLevel>(0);
+        assert_eq!(chatty.level, Level::Info);
+    }
+
+    #[test]
+    fn with_verbose_as_saturates_at_trace() {
+        let b = Builder::new().with_verbose_as::<StatusLevel>(100);
+        assert_eq!(b.level, Level::Trace);
+    }
+
+    #[test]
+    fn with_verbose_matches_with_verbose_as_status_level() {
+        for verbose in 0..=5u8 {
+            let a = Builder::new().with_verbose(verbose);
+            let b = Builder::new().with_verbose_as::<StatusLevel>(verbose);
+            assert_eq!(a.level, b.level);
+        }
+    }
+
+    struct RecordingBackend {
+        lines: Vec<String>,
+    }
+
+    impl Backend for RecordingBackend {
+        fn report(&mut self, _level: Level, args: fmt::Arguments) {
+            self.lines.push(args.to_string());
+        }
+    }
+
+    fn assert_send<T: Send>(_: T) {}
+
+    #[test]
+    fn boxed_custom_backend_is_send() {
+        let backend: Box<dyn Backend + Send> = Box::new(RecordingBackend { lines: Vec::new() });
+        assert_send(backend);
+    }
+
+    #[test]
+    fn use_color_never_and_always_ignore_the_stream() {
+        assert_eq!(UseColor::Never.resolve(atty::Stream::Stdout), ColorChoice::Never);
+        assert_eq!(UseColor::Never.resolve(atty::Stream::Stderr), ColorChoice::Never);
+        assert_eq!(UseColor::Always.resolve(atty::Stream::Stdout), ColorChoice::Always);
+        assert_eq!(UseColor::Always.resolve(atty::Stream::Stderr), ColorChoice::Always);
+    }
+
+    #[test]
+    fn default_report_action_right_aligns_the_verb() {
+        let mut backend = RecordingBackend { lines: Vec::new() };
+        backend.report_action(Level::Status, "Compiling", 12, format_args!("clout v0.1.0"));
+        assert_eq!(backend.lines, vec!["   Compiling clout v0.1.0".to_string()]);
+    }
+
+    #[test]
+    fn default_report_action_pads_to_the_given_width() {
+        let mut backend = RecordingBackend { lines: Vec::new() };
+        backend.report_action(Level::Status, "Go", 6, format_args!("{}", "short verb"));
+        assert_eq!(backend.lines, vec!["    Go short verb".to_string()]);
+    }
+}